@@ -0,0 +1,141 @@
+//! Runtime configuration for the Web Key Service.
+
+use std::{path::PathBuf, time::Duration};
+
+/// Which WKD URL layout(s) the server exposes.
+///
+/// [draft-koch] defines two variants: the `Advanced` layout (recommended,
+/// served from the `openpgpkey.<domain>` subdomain with the domain repeated
+/// in the path) and the `Direct` layout (served straight off `<domain>`,
+/// keyed off the `Host` header).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Variant {
+	Advanced,
+	Direct,
+}
+
+impl Default for Variant {
+	fn default() -> Self {
+		Self::Advanced
+	}
+}
+
+/// Which storage backend indexes and serves certificates
+///
+/// See [`crate::provider::FileKeyProvider`] and
+/// [`crate::provider::CertStoreProvider`].
+#[derive(Debug, Clone)]
+pub enum Backend {
+	/// A flat directory of key files, watched via inotify
+	File(PathBuf),
+	/// A `sequoia-cert-store` cert-d directory
+	CertStore(PathBuf),
+}
+
+impl Default for Backend {
+	fn default() -> Self {
+		Self::File(PathBuf::from("keys"))
+	}
+}
+
+/// Server-wide configuration.
+#[derive(Debug, Clone)]
+pub struct Config {
+	/// Which WKD layout(s) to serve routes for.
+	pub variants: Vec<Variant>,
+	/// Which storage backend indexes and serves certificates.
+	pub backend: Backend,
+	/// Email-ownership verification and key submission settings, if enabled.
+	pub submission: Option<SubmissionConfig>,
+	/// Upstream WKD fetch-through settings, if enabled. Off by default.
+	pub fallback: Option<FallbackConfig>,
+	/// Flags advertised in the `/policy` document.
+	pub policy: PolicyConfig,
+}
+
+impl Default for Config {
+	fn default() -> Self {
+		Self {
+			variants: vec![Variant::Advanced, Variant::Direct],
+			backend: Backend::default(),
+			submission: None,
+			fallback: None,
+			policy: PolicyConfig::default(),
+		}
+	}
+}
+
+impl Config {
+	/// Builds the [draft-koch] policy document advertised at `/policy`
+	pub fn policy_document(&self) -> String {
+		let mut lines = vec!["protocol-version: 3".to_string()];
+
+		if self.policy.mailbox_only {
+			lines.push("mailbox-only".to_string());
+		}
+		if self.policy.dane_only {
+			lines.push("dane-only".to_string());
+		}
+		if let Some(submission) = &self.submission {
+			lines.push("auth-submit".to_string());
+			lines.push(format!("submission-address: {}", submission.submission_address()));
+		}
+
+		lines.push(String::new());
+		lines.join("\n")
+	}
+}
+
+/// Flags advertised in the `/policy` document, independent of whether this
+/// server actually enforces them
+#[derive(Debug, Clone)]
+pub struct PolicyConfig {
+	/// This server only ever returns a certificate sanitized down to the
+	/// requested user ID (always true for this implementation; see
+	/// `provider::sanitize_cert`).
+	pub mailbox_only: bool,
+	/// Keys should only be trusted alongside DANE/TLSA validation for the domain.
+	pub dane_only: bool,
+}
+
+impl Default for PolicyConfig {
+	fn default() -> Self {
+		Self {
+			mailbox_only: true,
+			dane_only: false,
+		}
+	}
+}
+
+/// Settings for the upstream WKD fetch-through proxy
+#[derive(Debug, Clone)]
+pub struct FallbackConfig {
+	/// Domains this server is allowed to fetch-through for
+	pub allowlist: Vec<String>,
+	/// How long a fetched certificate is cached before being re-fetched
+	pub cache_ttl: Duration,
+	/// Whether a fetched certificate is also persisted into the local `KeyStore`
+	pub cache_locally: bool,
+}
+
+/// Settings for the self-submission and verification subsystem
+#[derive(Debug, Clone)]
+pub struct SubmissionConfig {
+	/// Base URL this server is reachable at, used to build `/verify/{token}` links
+	pub base_url: String,
+	/// Address mailed as the `From:` header on confirmation mail
+	pub from_address: String,
+	/// Mailbox administrators manually monitor for submissions from clients
+	/// that can't use the HTTP submission flow, per [draft-koch]'s
+	/// `submission-address` policy directive. Defaults to `from_address` when
+	/// unset; override only if that mailbox isn't actually read and
+	/// submissions should be directed elsewhere instead.
+	pub monitored_address: Option<String>,
+}
+
+impl SubmissionConfig {
+	/// The address advertised as `submission-address` in the policy document
+	pub fn submission_address(&self) -> &str {
+		self.monitored_address.as_deref().unwrap_or(&self.from_address)
+	}
+}
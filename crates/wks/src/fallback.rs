@@ -0,0 +1,133 @@
+//! Upstream WKD fallback: a caching fetch-through proxy for domains this
+//! server has no local key for
+//!
+//! Off by default, and only engaged for domains on the configured
+//! allowlist. A local miss is followed by a fetch of that domain's own WKD
+//! endpoint (Advanced first, falling back to Direct), and the verified
+//! result is cached for a bounded TTL so repeated lookups of the same key
+//! are answered without another upstream fetch. Failed fetches are not
+//! cached: each lookup of a key the upstream doesn't serve is fetched again.
+
+use std::{
+	collections::HashMap,
+	sync::RwLock,
+	time::{Duration, Instant},
+};
+
+use sequoia_openpgp::{Cert, parse::Parse, policy::StandardPolicy};
+
+use crate::{
+	WebKeyService,
+	provider::{KeyProvider, encode_local_part, sanitize_cert},
+};
+
+/// Fetches, verifies and caches keys from other domains' own WKD endpoints
+pub struct UpstreamClient {
+	http: reqwest::Client,
+	ttl: Duration,
+	cache: RwLock<HashMap<(String, String), (Cert, String, Instant)>>,
+}
+
+impl UpstreamClient {
+	pub fn new(ttl: Duration) -> Self {
+		Self {
+			http: reqwest::Client::new(),
+			ttl,
+			cache: RwLock::new(HashMap::new()),
+		}
+	}
+
+	/// Fetches `domain`'s own WKD endpoint for `hashed`, verifying the
+	/// result actually speaks for that (hashed local, domain) pair before
+	/// trusting it. Returns the sanitized certificate and the email it was
+	/// verified under.
+	pub(crate) async fn fetch(&self, domain: &str, hashed: &str, local: Option<&str>) -> Option<(Cert, String)> {
+		if let Some(cached) = self.cached(domain, hashed) {
+			return Some(cached);
+		}
+
+		let body = match self.get(&advanced_url(domain, hashed), local).await {
+			Some(body) => body,
+			None => self.get(&direct_url(domain, hashed), local).await?,
+		};
+
+		let cert = Cert::from_bytes(&body).ok()?;
+		let (cert, email) = verify(cert, hashed, domain)?;
+		self.store(domain, hashed, cert.clone(), email.clone());
+		Some((cert, email))
+	}
+
+	async fn get(&self, url: &str, local: Option<&str>) -> Option<Vec<u8>> {
+		let mut req = self.http.get(url);
+		if let Some(local) = local {
+			// Percent-encoded by `query`, unlike manual `format!` interpolation.
+			req = req.query(&[("l", local)]);
+		}
+		let resp = req.send().await.ok()?;
+		if !resp.status().is_success() {
+			return None;
+		}
+		resp.bytes().await.ok().map(|b| b.to_vec())
+	}
+
+	fn cached(&self, domain: &str, hashed: &str) -> Option<(Cert, String)> {
+		let cache = self.cache.read().ok()?;
+		let (cert, email, expires_at) = cache.get(&(domain.to_string(), hashed.to_string()))?;
+		(*expires_at > Instant::now()).then(|| (cert.clone(), email.clone()))
+	}
+
+	fn store(&self, domain: &str, hashed: &str, cert: Cert, email: String) {
+		if let Ok(mut cache) = self.cache.write() {
+			cache.insert(
+				(domain.to_string(), hashed.to_string()),
+				(cert, email, Instant::now() + self.ttl),
+			);
+		}
+	}
+}
+
+fn advanced_url(domain: &str, hashed: &str) -> String {
+	format!("https://openpgpkey.{domain}/.well-known/openpgpkeys/{domain}/hu/{hashed}")
+}
+
+fn direct_url(domain: &str, hashed: &str) -> String {
+	format!("https://{domain}/.well-known/openpgpkeys/hu/{hashed}")
+}
+
+/// Verifies the fetched cert normalizes to the requested hashed-local/domain
+/// pair, then sanitizes it down to that one user ID
+fn verify(cert: Cert, hashed: &str, domain: &str) -> Option<(Cert, String)> {
+	let p = StandardPolicy::new();
+	let valid = cert.with_policy(&p, None).ok()?;
+	let email = valid.userids().find_map(|uid| {
+		let email = uid.userid().email_normalized().ok().flatten()?;
+		let (local, uid_domain) = email.split_once('@')?;
+		(uid_domain == domain && encode_local_part(local) == hashed).then_some(email)
+	})?;
+	Some((sanitize_cert(cert, &email), email))
+}
+
+/// Fetches a certificate through the upstream fallback if it's configured
+/// and `domain` is allowlisted, optionally caching it into the local
+/// `KeyStore` so it's discoverable without another upstream fetch
+pub async fn fetch_if_enabled(
+	wks: &WebKeyService,
+	domain: &str,
+	hashed: &str,
+	local: Option<&str>,
+) -> Option<Cert> {
+	let config = wks.config.fallback.as_ref()?;
+	if !config.allowlist.iter().any(|allowed| allowed == domain) {
+		return None;
+	}
+
+	let (cert, email) = wks.upstream.fetch(domain, hashed, local).await?;
+
+	if config.cache_locally {
+		if let Err(err) = wks.provider.publish_uid(cert.clone(), &email) {
+			tracing::error!("Failed to cache fetched key for {email}: {err}");
+		}
+	}
+
+	Some(cert)
+}
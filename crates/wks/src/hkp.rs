@@ -0,0 +1,136 @@
+//! HKP (`pks/lookup`) keyserver interface, layered on top of [`KeyProvider`]
+//!
+//! This is not a full HKP server: there is no submission (`op=add`, handled
+//! separately by the verification subsystem) and searches only ever resolve
+//! to a single certificate, mirroring the rest of this store. It exists so
+//! that existing OpenPGP clients that speak HKP can query the same data WKD
+//! clients get.
+
+use axum::{
+	extract::{Query, State},
+	http::StatusCode,
+	response::{Html, IntoResponse},
+};
+use sequoia_openpgp::{
+	Cert, KeyHandle,
+	armor::{Kind, Writer},
+	serialize::Serialize,
+};
+use serde::Deserialize;
+
+use crate::{WebKeyService, provider::KeyProvider};
+
+/// Query parameters accepted by `GET /pks/lookup`
+#[derive(Debug, Deserialize)]
+pub struct LookupQuery {
+	op: Operation,
+	search: String,
+	options: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Operation {
+	Get,
+	Index,
+	Vindex,
+}
+
+/// `GET /pks/lookup`
+pub async fn lookup(
+	State(wks): State<WebKeyService>,
+	Query(query): Query<LookupQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+	let cert =
+		resolve(&wks, &query.search).ok_or((StatusCode::NOT_FOUND, "No results found".to_string()))?;
+
+	match query.op {
+		Operation::Get => {
+			let armored =
+				armor(&cert).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+			if machine_readable(query.options.as_deref()) {
+				Ok(armored.into_response())
+			} else {
+				Ok(Html(format!("<pre>\n{armored}</pre>\n")).into_response())
+			}
+		}
+		Operation::Index => Ok(index(&cert, false).into_response()),
+		Operation::Vindex => Ok(index(&cert, true).into_response()),
+	}
+}
+
+fn machine_readable(options: Option<&str>) -> bool {
+	options.is_some_and(|options| options.split(',').any(|o| o == "mr"))
+}
+
+/// Resolves an HKP `search` term (a `0x`-prefixed key handle or an email
+/// address) to a certificate
+fn resolve(wks: &WebKeyService, search: &str) -> Option<Cert> {
+	if let Some(hex) = search.strip_prefix("0x") {
+		let handle: KeyHandle = hex.parse().ok()?;
+		wks.provider.get_by_handle(&handle)
+	} else {
+		let (local, domain) = search.split_once('@')?;
+		wks.provider.lookup_email(local, domain)
+	}
+}
+
+/// ASCII-armors a certificate for transport
+fn armor(cert: &Cert) -> sequoia_openpgp::Result<String> {
+	let mut buf = Vec::new();
+	let mut writer = Writer::new(&mut buf, Kind::PublicKey)?;
+	cert.serialize(&mut writer)?;
+	writer.finalize()?;
+	Ok(String::from_utf8(buf).expect("armored output is ASCII"))
+}
+
+/// Builds the machine-readable `info:`/`pub:`/`uid:`[`/sub:`] listing used by
+/// `op=index` and `op=vindex`
+fn index(cert: &Cert, verbose: bool) -> String {
+	let key = cert.primary_key().key();
+	let mut out = format!(
+		"info:1:1\npub:{}:{}:{}:{}::\n",
+		cert.fingerprint().to_hex(),
+		u8::from(key.pk_algo()),
+		key.mpis().bits().unwrap_or(0),
+		unix_time(key.creation_time()),
+	);
+
+	for uid in cert.userids() {
+		out += &format!("uid:{}::\n", percent_encode(&uid.userid().to_string()));
+	}
+
+	if verbose {
+		for sk in cert.keys().subkeys() {
+			let key = sk.key();
+			out += &format!(
+				"sub:{}:{}:{}:{}::\n",
+				key.fingerprint().to_hex(),
+				u8::from(key.pk_algo()),
+				key.mpis().bits().unwrap_or(0),
+				unix_time(key.creation_time()),
+			);
+		}
+	}
+
+	out
+}
+
+fn unix_time(time: std::time::SystemTime) -> u64 {
+	time.duration_since(std::time::UNIX_EPOCH)
+		.map(|d| d.as_secs())
+		.unwrap_or_default()
+}
+
+/// Minimal percent-encoding for user IDs embedded in the colon-delimited format
+fn percent_encode(s: &str) -> String {
+	s.bytes()
+		.map(|b| {
+			if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+				(b as char).to_string()
+			} else {
+				format!("%{b:02X}")
+			}
+		})
+		.collect()
+}
@@ -1,27 +1,51 @@
 #![warn(clippy::pedantic)]
 
-use std::{ops::Deref, path::PathBuf, sync::Arc};
+use std::{
+	ops::Deref,
+	sync::{Arc, RwLock},
+};
 
 use axum::{
 	Router,
-	extract::{Path, Request, State},
+	extract::{Path, Query, Request, State},
 	http::StatusCode,
 	response::IntoResponse,
-	routing::get,
+	routing::{get, post},
 };
 use axum_extra::extract::Host;
 use proto::wks::web_key_service_server::{WebKeyService as WKS, WebKeyServiceServer};
 use runemail_proto as proto;
 use runesys::Service;
 use sequoia_openpgp::serialize::MarshalInto;
+use serde::Deserialize;
 
-use crate::provider::{FileKeyProvider, KeyProvider, KeyProviderType};
+use crate::{
+	config::{Backend, Config, Variant},
+	fallback::UpstreamClient,
+	pending::{LogMailer, MailerType, PendingStore},
+	provider::{CertStoreProvider, FileKeyProvider, KeyProvider, KeyProviderType},
+};
 
 mod config;
+mod fallback;
+mod hkp;
+mod pending;
 mod provider;
+mod submit;
 
 pub struct WKSInner {
 	pub provider: KeyProviderType,
+	pub config: Config,
+	pub pending: RwLock<PendingStore>,
+	pub mailer: MailerType,
+	pub upstream: UpstreamClient,
+}
+
+/// Query parameters accepted by the WKD `hu` lookup endpoints
+#[derive(Debug, Deserialize)]
+struct LookupQuery {
+	/// The un-hashed local part, included by some clients for diagnostics
+	l: Option<String>,
 }
 
 #[derive(Service, Clone)]
@@ -38,33 +62,89 @@ impl Deref for WebKeyService {
 
 impl WKS for WebKeyService {}
 
-async fn get_key(
+/// Direct variant: the domain is taken from the `Host` header
+async fn get_key_direct(
 	State(wks): State<WebKeyService>,
 	Path(local): Path<String>,
 	Host(host): Host,
+	Query(query): Query<LookupQuery>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-	if let Some(key) = wks.provider.discover(local, host) {
-		Ok(key.to_vec().unwrap().into_response())
-	} else {
-		Err((StatusCode::NOT_FOUND, "Not found".to_string()))
+	if let Some(key) = wks.provider.discover(&local, &host, query.l.as_deref()) {
+		return Ok(key.to_vec().unwrap().into_response());
+	}
+	if let Some(key) = fallback::fetch_if_enabled(&wks, &host, &local, query.l.as_deref()).await {
+		return Ok(key.to_vec().unwrap().into_response());
 	}
+	Err((StatusCode::NOT_FOUND, "Not found".to_string()))
 }
 
-async fn get_policy() -> String {
-	String::new()
+/// Advanced variant: the domain is taken from the path, not `Host`
+async fn get_key_advanced(
+	State(wks): State<WebKeyService>,
+	Path((domain, local)): Path<(String, String)>,
+	Query(query): Query<LookupQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+	if let Some(key) = wks.provider.discover(&local, &domain, query.l.as_deref()) {
+		return Ok(key.to_vec().unwrap().into_response());
+	}
+	if let Some(key) = fallback::fetch_if_enabled(&wks, &domain, &local, query.l.as_deref()).await {
+		return Ok(key.to_vec().unwrap().into_response());
+	}
+	Err((StatusCode::NOT_FOUND, "Not found".to_string()))
+}
+
+async fn get_policy(State(wks): State<WebKeyService>) -> String {
+	wks.config.policy_document()
 }
 
 fn app(wks: WebKeyService) -> Router {
-	Router::new()
-		.route("/.well-known/openpgpkeys/hu/{local}", get(get_key))
+	let mut router = Router::new()
 		.route("/.well-known/openpgpkeys/policy", get(get_policy))
-		.with_state(wks)
+		.route("/pks/lookup", get(hkp::lookup));
+
+	if wks.config.submission.is_some() {
+		router = router
+			.route("/.well-known/openpgpkeys/submit", post(submit::submit))
+			.route("/.well-known/openpgpkeys/unpublish", post(submit::unpublish))
+			.route("/verify/{token}", get(submit::verify_page).post(submit::verify));
+	}
+
+	for variant in &wks.config.variants {
+		router = match variant {
+			Variant::Advanced => router.route(
+				"/.well-known/openpgpkeys/{domain}/hu/{local}",
+				get(get_key_advanced),
+			),
+			Variant::Direct => router.route("/.well-known/openpgpkeys/hu/{local}", get(get_key_direct)),
+		};
+	}
+
+	router.with_state(wks)
+}
+
+/// Constructs the configured [`KeyProviderType`]
+fn build_provider(backend: &Backend) -> KeyProviderType {
+	match backend {
+		Backend::File(path) => FileKeyProvider::new(path.clone()).into(),
+		Backend::CertStore(path) => CertStoreProvider::new(path)
+			.expect("failed to open cert-store backend")
+			.into(),
+	}
 }
 
 #[tokio::main]
 async fn main() -> Result<(), runesys::error::Error> {
+	let config = Config::default();
+	let upstream_ttl = config
+		.fallback
+		.as_ref()
+		.map_or(std::time::Duration::from_secs(3600), |fallback| fallback.cache_ttl);
 	let wks = WebKeyService(Arc::new(WKSInner {
-		provider: FileKeyProvider::new(PathBuf::from("keys")).into(),
+		provider: build_provider(&config.backend),
+		config,
+		pending: RwLock::new(PendingStore::default()),
+		mailer: MailerType::from(LogMailer),
+		upstream: UpstreamClient::new(upstream_ttl),
 	}));
 	let app = app(wks.clone());
 
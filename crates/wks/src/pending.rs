@@ -0,0 +1,159 @@
+//! Email-ownership verification for self-submitted keys
+//!
+//! Keys dropped into the watched directory are trusted outright, but a key
+//! submitted over HTTP is not: anyone could upload a cert claiming someone
+//! else's address. [`PendingStore`] stashes a submission under a random
+//! opaque token and only promotes it into the live `KeyStore` once that
+//! token comes back via a confirmed `POST /verify/{token}`, reached by
+//! following a `GET /verify/{token}` link mailed to the address in
+//! question. The `GET` only renders a confirmation step: applying the
+//! mutation on a bare `GET` would let mail link-scanners silently confirm
+//! submissions no one actually clicked on.
+
+use std::{
+	collections::HashMap,
+	time::{Duration, SystemTime},
+};
+
+use enum_dispatch::enum_dispatch;
+use rand::{Rng, distributions::Alphanumeric};
+use sequoia_openpgp::{Cert, Fingerprint};
+use thiserror::Error;
+
+/// How long a confirmation token remains valid
+const TOKEN_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub type Result<T> = std::result::Result<T, PendingError>;
+
+#[derive(Error, Debug)]
+pub enum PendingError {
+	#[error("token not found or expired")]
+	InvalidToken,
+}
+
+/// What a confirmed token does to the live `KeyStore`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+	/// Promote the pending user ID into the live store
+	Publish,
+	/// Remove the user ID from the live store
+	Unpublish,
+}
+
+/// A single (email, fingerprint) pair awaiting confirmation
+struct Pending {
+	email: String,
+	fingerprint: Fingerprint,
+	/// The submitted certificate, required to publish; absent for unpublish
+	/// requests, which only need the email/fingerprint already on file.
+	cert: Option<Cert>,
+	action: Action,
+	expires_at: SystemTime,
+}
+
+/// The outcome of confirming a token
+pub struct Confirmed {
+	pub email: String,
+	pub fingerprint: Fingerprint,
+	pub cert: Option<Cert>,
+	pub action: Action,
+}
+
+/// Tracks opaque confirmation tokens for pending key submissions
+#[derive(Default)]
+pub struct PendingStore {
+	tokens: HashMap<String, Pending>,
+}
+
+impl PendingStore {
+	/// Stashes a pending submission and returns its opaque token
+	///
+	/// Also sweeps out already-expired tokens, so an unauthenticated caller
+	/// hammering `/submit` with throwaway certs can't grow this map forever.
+	pub fn stash(
+		&mut self,
+		email: String,
+		fingerprint: Fingerprint,
+		cert: Option<Cert>,
+		action: Action,
+	) -> String {
+		self.evict_expired();
+
+		let token = generate_token();
+		self.tokens.insert(
+			token.clone(),
+			Pending {
+				email,
+				fingerprint,
+				cert,
+				action,
+				expires_at: SystemTime::now() + TOKEN_TTL,
+			},
+		);
+		token
+	}
+
+	/// Looks at a pending token without consuming it, for rendering a
+	/// confirmation page before the mutation is actually applied
+	pub fn peek(&self, token: &str) -> Result<(&str, Action)> {
+		let pending = self.tokens.get(token).ok_or(PendingError::InvalidToken)?;
+		if pending.expires_at < SystemTime::now() {
+			return Err(PendingError::InvalidToken);
+		}
+		Ok((&pending.email, pending.action))
+	}
+
+	/// Confirms a token, removing it and returning what should be applied to
+	/// the live store
+	pub fn confirm(&mut self, token: &str) -> Result<Confirmed> {
+		let pending = self.tokens.remove(token).ok_or(PendingError::InvalidToken)?;
+		if pending.expires_at < SystemTime::now() {
+			return Err(PendingError::InvalidToken);
+		}
+		Ok(Confirmed {
+			email: pending.email,
+			fingerprint: pending.fingerprint,
+			cert: pending.cert,
+			action: pending.action,
+		})
+	}
+
+	/// Removes all expired, unconfirmed tokens
+	fn evict_expired(&mut self) {
+		let now = SystemTime::now();
+		self.tokens.retain(|_, pending| pending.expires_at >= now);
+	}
+}
+
+fn generate_token() -> String {
+	rand::thread_rng()
+		.sample_iter(&Alphanumeric)
+		.take(32)
+		.map(char::from)
+		.collect()
+}
+
+/// Sends confirmation mail for the submission/verification flow
+///
+/// Deployments wire in an SMTP-backed implementation; [`LogMailer`] is the
+/// default used when none is configured.
+#[enum_dispatch]
+pub trait Mailer {
+	/// Sends a plain-text email
+	async fn send(&self, to: &str, subject: &str, body: &str);
+}
+
+#[enum_dispatch(Mailer)]
+pub enum MailerType {
+	LogMailer(LogMailer),
+}
+
+/// A [`Mailer`] that logs the message instead of sending it
+#[derive(Default, Clone)]
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+	async fn send(&self, to: &str, subject: &str, body: &str) {
+		tracing::info!(%to, %subject, %body, "would send confirmation mail");
+	}
+}
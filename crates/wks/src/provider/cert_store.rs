@@ -0,0 +1,161 @@
+//! `KeyProvider` backed by [`sequoia_cert_store`]
+//!
+//! Unlike [`super::FileKeyProvider`], which keeps everything in three
+//! in-memory `HashMap`s rebuilt from a full directory scan on every start,
+//! this backend persists certificates in the on-disk `cert-d` layout and
+//! relies on `sequoia-cert-store`'s own fingerprint/subkey indices instead
+//! of re-deriving them at startup. The one exception is the hashed
+//! local-part index used by WKD lookups, which is rebuilt at startup from
+//! every cert in the store (see [`CertStoreProvider`]).
+
+use std::{borrow::Cow, collections::HashMap, path::Path, sync::RwLock};
+
+use sequoia_cert_store::{
+	CertStore as SqCertStore, LazyCert,
+	store::{Store, StoreUpdate},
+};
+use sequoia_openpgp::{Cert, Fingerprint, KeyHandle, policy::StandardPolicy};
+
+use super::{KeyError, KeyProvider, Result, encode_local_part, sanitize_cert};
+
+/// `KeyProvider` backed by a `sequoia-cert-store` cert-d directory
+///
+/// WKD lookups need to go from a *hashed* local part back to a certificate,
+/// but `sequoia-cert-store`'s own indices are keyed on the plain address.
+/// `email_index` is a small in-memory cache mapping `(hashed local part,
+/// domain)` to the matching fingerprint and normalized email. It is built
+/// once from the certs already on disk when the provider is opened, then
+/// kept in sync incrementally as certs are published/unpublished.
+pub struct CertStoreProvider {
+	store: RwLock<SqCertStore<'static>>,
+	email_index: RwLock<HashMap<(String, String), (Fingerprint, String)>>,
+}
+
+impl CertStoreProvider {
+	/// Opens (or creates) a cert-d directory at `path`
+	pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+		let store = SqCertStore::open(path.as_ref()).map_err(KeyError::Other)?;
+		let provider = Self {
+			store: RwLock::new(store),
+			email_index: RwLock::new(HashMap::new()),
+		};
+		provider.reindex();
+		Ok(provider)
+	}
+
+	/// Builds `email_index` from every cert already in the store
+	///
+	/// Only needed once, at startup, so certs persisted by a previous run are
+	/// discoverable: after that `upsert`/`unpublish_uid` keep the index in
+	/// sync incrementally, without another pass over the store.
+	fn reindex(&self) {
+		let Ok(store) = self.store.read() else {
+			return;
+		};
+		for cert in store.certs() {
+			if let Ok(cert) = cert.to_cert() {
+				self.index(cert);
+			}
+		}
+	}
+
+	fn get_cert(&self, fp: &Fingerprint) -> Option<Cert> {
+		let store = self.store.read().ok()?;
+		store.lookup_by_cert_fpr(fp).ok()?.to_cert().ok().cloned()
+	}
+
+	/// Indexes a certificate's user-ID emails for hashed lookups
+	fn index(&self, cert: &Cert) {
+		let p = StandardPolicy::new();
+		let Ok(valid) = cert.with_policy(&p, None) else {
+			return;
+		};
+		let Ok(mut index) = self.email_index.write() else {
+			return;
+		};
+		for uid in valid.userids() {
+			let Ok(Some(email)) = uid.userid().email_normalized() else {
+				continue;
+			};
+			let Some((local, domain)) = email.split_once('@') else {
+				continue;
+			};
+			index.insert(
+				(encode_local_part(local), domain.to_string()),
+				(cert.fingerprint(), email.clone()),
+			);
+		}
+	}
+
+	/// Indexes and persists a certificate, merging with any existing one
+	/// under the same fingerprint (handled by the underlying cert-d store)
+	fn upsert(&self, cert: Cert) -> Result<()> {
+		self.index(&cert);
+		let store = self.store.write().map_err(|_| KeyError::Lock)?;
+		store
+			.update(Cow::Owned(LazyCert::from(cert)))
+			.map_err(KeyError::Other)
+	}
+}
+
+impl KeyProvider for CertStoreProvider {
+	fn discover<S: AsRef<str>>(&self, hashed: S, domain: S, local: Option<&str>) -> Option<Cert> {
+		let key = match local {
+			Some(local) => (encode_local_part(local), domain.as_ref().to_string()),
+			None => (hashed.as_ref().to_string(), domain.as_ref().to_string()),
+		};
+
+		let (fp, email) = {
+			let index = self.email_index.read().ok()?;
+			index.get(&key).cloned()
+		}?;
+		let cert = self.get_cert(&fp)?;
+		Some(sanitize_cert(cert, &email))
+	}
+
+	fn lookup_email(&self, local_part: &str, domain: &str) -> Option<Cert> {
+		let fp = {
+			let index = self.email_index.read().ok()?;
+			index
+				.get(&(encode_local_part(local_part), domain.to_string()))
+				.map(|(fp, _)| fp.clone())
+		}?;
+		self.get_cert(&fp)
+	}
+
+	fn get_by_handle(&self, handle: &KeyHandle) -> Option<Cert> {
+		let store = self.store.read().ok()?;
+		store
+			.lookup_by_cert(handle)
+			.ok()?
+			.into_iter()
+			.next()?
+			.to_cert()
+			.ok()
+			.cloned()
+	}
+
+	fn publish_uid(&self, cert: Cert, email: &str) -> Result<()> {
+		let cert = cert.retain_userids(|ua| {
+			ua.userid().email_normalized().ok().flatten().as_deref() == Some(email)
+		});
+		self.upsert(cert)
+	}
+
+	fn unpublish_uid(&self, fp: &Fingerprint, email: &str) -> Result<()> {
+		let cert = self.get_cert(fp).ok_or(KeyError::KeyNotFound)?;
+		let remaining = cert.retain_userids(|ua| {
+			ua.userid().email_normalized().ok().flatten().as_deref() != Some(email)
+		});
+
+		if let Some((local, domain)) = email.split_once('@') {
+			if let Ok(mut index) = self.email_index.write() {
+				index.remove(&(encode_local_part(local), domain.to_string()));
+			}
+		}
+
+		// cert-d has no delete primitive; a user-id-less cert is simply
+		// unreachable from discover/lookup_email/get_by_handle going forward.
+		self.upsert(remaining)
+	}
+}
@@ -8,12 +8,15 @@ use std::{
 use enum_dispatch::enum_dispatch;
 use inotify::{EventMask, Inotify, WatchMask};
 use sequoia_openpgp::{
-	Cert, Fingerprint, parse::Parse, policy::StandardPolicy, types::HashAlgorithm,
+	Cert, Fingerprint, KeyHandle, parse::Parse, policy::StandardPolicy, types::HashAlgorithm,
 };
 use thiserror::Error;
 use tokio::task::JoinHandle;
 use tokio_stream::StreamExt;
 
+mod cert_store;
+pub use cert_store::CertStoreProvider;
+
 /// Type alias for results returned by this module
 pub type Result<T> = std::result::Result<T, KeyError>;
 
@@ -24,6 +27,8 @@ pub enum KeyError {
 	KeyNotFound,
 	#[error("file not found")]
 	FileNotFound,
+	#[error("lock poisoned")]
+	Lock,
 	#[error(transparent)]
 	Other(#[from] sequoia_openpgp::anyhow::Error),
 }
@@ -42,12 +47,42 @@ struct EmailComponents {
 #[enum_dispatch]
 pub trait KeyProvider {
 	/// Discovers a certificate based on the hashed local part and domain of an email
-	fn discover<S: AsRef<str>>(&self, hashed: S, domain: S) -> Option<Cert>;
+	///
+	/// If `local` is set (from the WKD `?l=` query parameter), it takes
+	/// precedence over `hashed` and is matched against the un-hashed local
+	/// part directly.
+	fn discover<S: AsRef<str>>(&self, hashed: S, domain: S, local: Option<&str>) -> Option<Cert>;
+
+	/// Looks up a certificate by its un-hashed local part and domain
+	///
+	/// Unlike [`KeyProvider::discover`], the returned certificate is not
+	/// sanitized down to a single user ID. Used by the HKP interface, where
+	/// clients expect the full certificate back.
+	fn lookup_email(&self, local_part: &str, domain: &str) -> Option<Cert>;
+
+	/// Looks up a certificate by fingerprint or long (64-bit) key ID
+	///
+	/// Short (32-bit) key IDs don't parse into a usable [`KeyHandle`] and
+	/// match no certificate.
+	fn get_by_handle(&self, handle: &KeyHandle) -> Option<Cert>;
+
+	/// Promotes a single confirmed user ID into the live store
+	///
+	/// Only `email` is processed; any other user IDs on `cert` are ignored
+	/// and remain pending until separately confirmed.
+	fn publish_uid(&self, cert: Cert, email: &str) -> Result<()>;
+
+	/// Removes a single user ID from the live store
+	///
+	/// If it was the only user ID on the certificate, the certificate itself
+	/// is removed.
+	fn unpublish_uid(&self, fp: &Fingerprint, email: &str) -> Result<()>;
 }
 
 #[enum_dispatch(KeyProvider)]
 pub enum KeyProviderType {
 	FileKeyProvider(FileKeyProvider),
+	CertStoreProvider(CertStoreProvider),
 }
 
 /// Stores and manages PGP certificates
@@ -65,7 +100,7 @@ pub struct KeyStore {
 ///     resulting 160-bit digest is encoded using the Z-Base-32 method as
 ///     described in RFC6189, section 5.1.6. The resulting string has a
 ///     fixed length of 32 octets.
-fn encode_local_part<S: AsRef<str>>(local_part: S) -> String {
+pub(crate) fn encode_local_part<S: AsRef<str>>(local_part: S) -> String {
 	let local_part = local_part.as_ref();
 	let mut digest = vec![0; 20];
 	let mut ctx = HashAlgorithm::SHA1
@@ -163,6 +198,64 @@ impl KeyStore {
 				})
 			})
 	}
+
+	/// Finds a certificate by its un-hashed local part and domain
+	///
+	/// Used when a client supplies the optional `?l=` query parameter from
+	/// [draft-koch], which carries the un-hashed local part for diagnostics.
+	pub fn find_by_local_part(&self, local_part: &str, domain: &str) -> Option<(String, String, Cert)> {
+		self.uids
+			.iter()
+			.find(|(components, _)| {
+				components.local_part == local_part && components.domain == domain
+			})
+			.and_then(|(components, fp)| {
+				self.get(fp).cloned().map(|cert| {
+					(
+						components.local_part.clone(),
+						components.domain.clone(),
+						cert,
+					)
+				})
+			})
+	}
+
+	/// Finds a certificate by fingerprint or long (64-bit) key ID of its
+	/// primary key; short (32-bit) key IDs match nothing
+	pub fn get_by_handle(&self, handle: &KeyHandle) -> Option<&Cert> {
+		self.keys
+			.iter()
+			.find(|(fp, _)| KeyHandle::from((*fp).clone()).aliases(handle))
+			.map(|(_, cert)| cert)
+	}
+
+	/// Promotes a single confirmed user ID into the live store, merging with
+	/// any existing certificate under the same fingerprint
+	pub fn publish_uid(&mut self, cert: Cert, email: &str) -> Result<()> {
+		let cert = cert.retain_userids(|ua| {
+			ua.userid().email_normalized().ok().flatten().as_deref() == Some(email)
+		});
+		self.import(cert)
+	}
+
+	/// Removes a single user ID from the live store
+	pub fn unpublish_uid(&mut self, fp: &Fingerprint, email: &str) -> Result<()> {
+		let cert = self.keys.get(fp).cloned().ok_or(KeyError::KeyNotFound)?;
+		let remaining = cert.retain_userids(|ua| {
+			ua.userid().email_normalized().ok().flatten().as_deref() != Some(email)
+		});
+
+		self.uids.retain(|components, v| {
+			v != fp || format!("{}@{}", components.local_part, components.domain) != email
+		});
+
+		if remaining.userids().count() == 0 {
+			self.keys.remove(fp);
+		} else {
+			self.keys.insert(fp.clone(), remaining);
+		}
+		Ok(())
+	}
 }
 
 /// Monitors a directory for PGP key files and maintains a key store
@@ -275,7 +368,7 @@ impl Drop for FileKeyProvider {
 /// 1. Keeps only the UserID for the target email
 /// 2. Removes any UserAttributes (photos, etc.)
 /// 3. Retains only subkeys that can encrypt or sign
-fn sanitize_cert(cert: Cert, target_email: &str) -> Cert {
+pub(crate) fn sanitize_cert(cert: Cert, target_email: &str) -> Cert {
 	// 1. Keep only the one UserID we care about.
 	let cert = cert.retain_userids(|ua| ua.userid().email().ok().flatten() == Some(target_email));
 	// 2. Strip out any UserAttributes (e.g. photo packets).
@@ -291,11 +384,33 @@ fn sanitize_cert(cert: Cert, target_email: &str) -> Cert {
 }
 
 impl KeyProvider for FileKeyProvider {
-	fn discover<S: AsRef<str>>(&self, encoded: S, domain: S) -> Option<Cert> {
+	fn discover<S: AsRef<str>>(&self, encoded: S, domain: S, local: Option<&str>) -> Option<Cert> {
 		let keys = self.keys.read().ok()?;
-		keys.find_by_email(encoded.as_ref(), domain.as_ref())
-			.map(|(local_part, domain, cert)| {
-				sanitize_cert(cert, &format!("{local_part}@{domain}"))
-			})
+		let found = match local {
+			Some(local) => keys.find_by_local_part(local, domain.as_ref()),
+			None => keys.find_by_email(encoded.as_ref(), domain.as_ref()),
+		};
+		found.map(|(local_part, domain, cert)| sanitize_cert(cert, &format!("{local_part}@{domain}")))
+	}
+
+	fn lookup_email(&self, local_part: &str, domain: &str) -> Option<Cert> {
+		let keys = self.keys.read().ok()?;
+		let encoded = encode_local_part(local_part);
+		keys.find_by_email(&encoded, domain).map(|(_, _, cert)| cert)
+	}
+
+	fn get_by_handle(&self, handle: &KeyHandle) -> Option<Cert> {
+		let keys = self.keys.read().ok()?;
+		keys.get_by_handle(handle).cloned()
+	}
+
+	fn publish_uid(&self, cert: Cert, email: &str) -> Result<()> {
+		let mut keys = self.keys.write().map_err(|_| KeyError::Lock)?;
+		keys.publish_uid(cert, email)
+	}
+
+	fn unpublish_uid(&self, fp: &Fingerprint, email: &str) -> Result<()> {
+		let mut keys = self.keys.write().map_err(|_| KeyError::Lock)?;
+		keys.unpublish_uid(fp, email)
 	}
 }
@@ -0,0 +1,175 @@
+//! HTTP endpoints for the self-submission and verification flow
+//!
+//! See [`crate::pending`] for the token lifecycle.
+
+use axum::{
+	body::Bytes,
+	extract::{Form, Path, State},
+	http::StatusCode,
+	response::{Html, IntoResponse},
+};
+use sequoia_openpgp::{Cert, packet::UserID, parse::Parse};
+use serde::Deserialize;
+
+use crate::{
+	WebKeyService,
+	pending::{Action, Mailer},
+	provider::KeyProvider,
+};
+
+fn lock_poisoned() -> (StatusCode, String) {
+	(StatusCode::INTERNAL_SERVER_ERROR, "lock poisoned".to_string())
+}
+
+/// Escapes a string for safe interpolation into HTML text/attribute content
+///
+/// `email` below traces back to attacker-controlled bytes (a user ID on a
+/// self-submitted cert, or the raw `email` form field on `/unpublish`), so
+/// it can't be interpolated into [`Html`] unescaped.
+fn escape_html(s: &str) -> String {
+	s.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+		.replace('\'', "&#39;")
+}
+
+/// Normalizes a bare email address the same way submitted certs' user IDs
+/// are normalized, so the two paths can be compared for equality
+fn normalize_email(email: &str) -> Option<String> {
+	UserID::from(format!("<{email}>")).email_normalized().ok().flatten()
+}
+
+/// `POST /.well-known/openpgpkeys/submit`
+///
+/// Accepts a single certificate, stashes each of its user-ID emails as a
+/// pending submission and mails a confirmation link to each one.
+pub async fn submit(
+	State(wks): State<WebKeyService>,
+	body: Bytes,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+	let Some(submission) = &wks.config.submission else {
+		return Err((StatusCode::NOT_FOUND, "Not found".to_string()));
+	};
+
+	let cert = Cert::from_bytes(&body).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+	let fp = cert.fingerprint();
+
+	let p = sequoia_openpgp::policy::StandardPolicy::new();
+	let valid = cert
+		.with_policy(&p, None)
+		.map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+	let mut submitted = 0;
+	for uid in valid.userids() {
+		let Ok(Some(email)) = uid.userid().email_normalized() else {
+			continue;
+		};
+
+		let token = {
+			let mut pending = wks.pending.write().map_err(|_| lock_poisoned())?;
+			pending.stash(email.clone(), fp.clone(), Some(cert.clone()), Action::Publish)
+		};
+		send_confirmation(&wks, &submission.base_url, &email, &token).await;
+		submitted += 1;
+	}
+
+	Ok(format!("{submitted} user ID(s) pending confirmation"))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UnpublishRequest {
+	email: String,
+}
+
+/// `POST /.well-known/openpgpkeys/unpublish`
+///
+/// Looks up the live cert for `email` and mails a managed link that removes
+/// just that user ID once confirmed. Does not reveal whether the address
+/// was actually found.
+pub async fn unpublish(
+	State(wks): State<WebKeyService>,
+	Form(request): Form<UnpublishRequest>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+	let Some(submission) = &wks.config.submission else {
+		return Err((StatusCode::NOT_FOUND, "Not found".to_string()));
+	};
+
+	if let Some(email) = normalize_email(&request.email) {
+		if let Some((local, domain)) = email.split_once('@') {
+			if let Some(cert) = wks.provider.lookup_email(local, domain) {
+				let token = {
+					let mut pending = wks.pending.write().map_err(|_| lock_poisoned())?;
+					pending.stash(email.clone(), cert.fingerprint(), None, Action::Unpublish)
+				};
+				send_confirmation(&wks, &submission.base_url, &email, &token).await;
+			}
+		}
+	}
+
+	Ok("If that address has a published key, a confirmation link has been sent")
+}
+
+/// `GET /verify/{token}`
+///
+/// Renders a confirmation step instead of applying the action directly, so
+/// that mail link-scanners pre-fetching this URL can't silently confirm a
+/// submission no one actually clicked on.
+pub async fn verify_page(
+	State(wks): State<WebKeyService>,
+	Path(token): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+	let pending = wks.pending.read().map_err(|_| lock_poisoned())?;
+	let (email, action) = pending
+		.peek(&token)
+		.map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+
+	let verb = match action {
+		Action::Publish => "publish",
+		Action::Unpublish => "unpublish",
+	};
+
+	Ok(Html(format!(
+		"<p>Confirm that {} should be {verb}ed.</p>\
+		 <form method=\"post\"><button type=\"submit\">Confirm</button></form>",
+		escape_html(email),
+	)))
+}
+
+/// `POST /verify/{token}`
+///
+/// Applies the pending action. Only reached once the confirmation step
+/// rendered by [`verify_page`] has actually been submitted.
+pub async fn verify(
+	State(wks): State<WebKeyService>,
+	Path(token): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+	let confirmed = {
+		let mut pending = wks.pending.write().map_err(|_| lock_poisoned())?;
+		pending
+			.confirm(&token)
+			.map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?
+	};
+
+	match (confirmed.action, confirmed.cert) {
+		(Action::Publish, Some(cert)) => {
+			wks.provider
+				.publish_uid(cert, &confirmed.email)
+				.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+			Ok(format!("{} is now published", confirmed.email))
+		}
+		(Action::Unpublish, _) => {
+			wks.provider
+				.unpublish_uid(&confirmed.fingerprint, &confirmed.email)
+				.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+			Ok(format!("{} has been unpublished", confirmed.email))
+		}
+		(Action::Publish, None) => Err((StatusCode::INTERNAL_SERVER_ERROR, "missing certificate".to_string())),
+	}
+}
+
+async fn send_confirmation(wks: &WebKeyService, base_url: &str, email: &str, token: &str) {
+	let link = format!("{base_url}/verify/{token}");
+	let body = format!("Please confirm this request by visiting:\n\n{link}\n");
+	wks.mailer.send(email, "Confirm your key submission", &body).await;
+}